@@ -0,0 +1,144 @@
+//! Writes decrypted entries directly into a tar or zip container instead of
+//! loose files on disk.
+
+use std::collections::HashMap;
+use std::io::{self, Error, Read, Seek, Write};
+
+use crate::manifest::{ChecksumReader, ChecksumWriter, ManifestEntry};
+use crate::RGSSArchive;
+
+fn check(name: &str, size: u32, crc32: u32, manifest: Option<&HashMap<String, ManifestEntry>>) -> io::Result<()> {
+    let Some(manifest) = manifest else { return Ok(()) };
+    match manifest.get(name) {
+        Some(expected) if expected.crc32 == crc32 && expected.size == size => Ok(()),
+        Some(expected) => Err(Error::other(format!(
+            "checksum mismatch for {}: expected {:08x}, got {:08x}", name, expected.crc32, crc32
+        ))),
+        None => Err(Error::other(format!("{} not present in manifest", name))),
+    }
+}
+
+#[cfg(feature = "tar")]
+pub fn write_tar<S: Read + Seek, W: Write>(
+    archive: &mut RGSSArchive<S>,
+    indices: &[usize],
+    out: &mut W,
+    manifest: Option<&HashMap<String, ManifestEntry>>,
+) -> io::Result<()> {
+    let mut builder = tar::Builder::new(out);
+
+    for &i in indices {
+        let (name, data) = {
+            let entry = &archive.entries()[i];
+            (entry.name.clone(), entry.data)
+        };
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.size as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+
+        let mut reader = ChecksumReader::new(archive.entry_reader(&data)?);
+        builder.append_data(&mut header, &name, &mut reader)?;
+        check(&name, data.size, reader.finish(), manifest)?;
+    }
+
+    builder.finish()
+}
+
+#[cfg(feature = "zip")]
+pub fn write_zip<S: Read + Seek, W: Write + Seek>(
+    archive: &mut RGSSArchive<S>,
+    indices: &[usize],
+    out: W,
+    manifest: Option<&HashMap<String, ManifestEntry>>,
+) -> io::Result<()> {
+    let mut zip = zip::ZipWriter::new(out);
+    let options = zip::write::FileOptions::default();
+
+    for &i in indices {
+        let (name, data) = {
+            let entry = &archive.entries()[i];
+            (entry.name.clone(), entry.data)
+        };
+
+        zip.start_file(&name, options).map_err(|e| Error::other(e.to_string()))?;
+        let mut writer = ChecksumWriter::new(&mut zip);
+        archive.extract_entry(&data, &mut writer)?;
+        let (_, crc32) = writer.finish();
+        check(&name, data.size, crc32, manifest)?;
+    }
+
+    zip.finish().map_err(|e| Error::other(e.to_string()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collect_entries;
+    use std::fs;
+    use std::io::Cursor;
+
+    fn build_test_archive() -> RGSSArchive<Cursor<Vec<u8>>> {
+        let dir = std::env::temp_dir().join(format!("rgssad_container_test_{}", std::process::id()));
+        let src = dir.join("src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("one.txt"), b"hello").unwrap();
+        fs::write(src.join("two.txt"), b"world").unwrap();
+
+        let mut archive = RGSSArchive::create(Cursor::new(Vec::new()), 1).unwrap();
+        collect_entries(&mut archive, &src, &src).unwrap();
+        archive.write_entries(&src, true, None).unwrap();
+        let bytes = archive.stream.into_inner();
+
+        fs::remove_dir_all(&dir).ok();
+        RGSSArchive::open(Cursor::new(bytes)).unwrap()
+    }
+
+    #[cfg(feature = "tar")]
+    #[test]
+    fn write_tar_round_trips_entry_contents() {
+        let mut archive = build_test_archive();
+        let indices: Vec<usize> = (0..archive.entries().len()).collect();
+
+        let mut buf = vec![];
+        write_tar(&mut archive, &indices, &mut buf, None).unwrap();
+
+        let mut found: HashMap<String, Vec<u8>> = HashMap::new();
+        let mut reader = tar::Archive::new(Cursor::new(buf));
+        for file in reader.entries().unwrap() {
+            let mut file = file.unwrap();
+            let path = file.path().unwrap().to_string_lossy().into_owned();
+            let mut content = vec![];
+            file.read_to_end(&mut content).unwrap();
+            found.insert(path, content);
+        }
+
+        assert_eq!(found.get("one.txt").unwrap(), b"hello");
+        assert_eq!(found.get("two.txt").unwrap(), b"world");
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn write_zip_round_trips_entry_contents() {
+        let mut archive = build_test_archive();
+        let indices: Vec<usize> = (0..archive.entries().len()).collect();
+
+        let mut buf = Cursor::new(vec![]);
+        write_zip(&mut archive, &indices, &mut buf, None).unwrap();
+
+        let mut reader = zip::ZipArchive::new(buf).unwrap();
+        let mut found: HashMap<String, Vec<u8>> = HashMap::new();
+        for i in 0..reader.len() {
+            let mut file = reader.by_index(i).unwrap();
+            let name = file.name().to_string();
+            let mut content = vec![];
+            file.read_to_end(&mut content).unwrap();
+            found.insert(name, content);
+        }
+
+        assert_eq!(found.get("one.txt").unwrap(), b"hello");
+        assert_eq!(found.get("two.txt").unwrap(), b"world");
+    }
+}
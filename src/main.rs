@@ -1,344 +1,18 @@
+use std::collections::HashMap;
+use std::env;
 use std::fs;
-use std::fs::File;
-use std::io::SeekFrom;
-use std::io::Seek;
-use std::io::Read;
+use std::io;
 use std::io::Write;
-use std::io::Error;
-use std::io::ErrorKind;
-use std::env;
 use std::path::Path;
-use std::convert::TryInto;
 
 extern crate regex;
 use regex::Regex;
 
-static __VERSION__: &str = "0.1.4";
-
-// Errors
-static E_INVALIDHDR: &str = "Input file header mismatch.";
-static E_INVALIDVER: &str = "Not supported version (must be 1-3).";
-static E_INVALIDMGC: &str = "Magic number read failed.";
-
-
-fn advance_magic(magic: &mut u32) -> u32 {
-    let old = *magic;
-    *magic = magic.wrapping_mul(7).wrapping_add(3);
-    old
-}
-
-fn ru32(stream: &mut File, result: &mut u32) -> Result<(), Error> {
-    let mut buf = [0; 4];
-    stream.read_exact(&mut buf)?;
-    *result = u32::from_le_bytes(buf);
-    Ok(())
-}
-
-fn wu32(stream: &mut File, data: u32) -> Result<(), Error> {
-    let buf = data.to_le_bytes();
-    stream.write_all(&buf)
-}
-
-/// Calls read until the buffer is full or EOF.
-fn read_until_full(stream: &mut File, buf: &mut [u8]) -> Result<usize, Error> {
-    let mut nb = 0;
-    loop {
-        match stream.read(&mut buf[nb..]) {
-            Ok(0) => return Ok(nb),
-            Ok(n) => nb += n,
-            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
-            Err(e) => return Err(e),
-        }
-    }
-}
-
-struct EntryData {
-    offset: u32,
-    magic: u32,
-    size: u32,
-}
-
-struct Coder {
-    buf: Vec<u8>,
-}
-
-impl Coder {
-    /// Encrypts/decrypts file data from stream_in to stream_out.
-    fn copy(
-        &mut self,
-        stream_in: &mut File,
-        stream_out: &mut File,
-        data: &EntryData,
-    ) -> Result<(), Error> {
-        assert!(self.buf.len() % 4 == 0); // needed for alignment
-
-        stream_in.seek(SeekFrom::Start(data.offset as u64))?;
-
-        let mut magic = data.magic;
-        let mut size = data.size; // remaining bytes to read
-        loop {
-            let limit = self.buf.len().min(size as usize);
-            let count = read_until_full(stream_in, &mut self.buf[..limit])?;
-            if count == 0 { return Ok(()) }
-            let buf = &mut self.buf[..count];
-
-            let (prefix, middle, suffix) = unsafe { buf.align_to_mut::<u32>() };
-            assert!(prefix.len() == 0); // assume buf is aligned
-
-            for i in 0..middle.len() {
-                let mut w = u32::from_le(middle[i]);
-                w ^= advance_magic(&mut magic);
-                middle[i] = w.to_le();
-            }
-
-            for i in 0..suffix.len() {
-                suffix[i] ^= (magic >> (i * 8)) as u8;
-            }
-
-            size -= count as u32;
-            stream_out.write_all(buf)?;
-        }
-    }
-}
-
-struct Entry {
-    name: String,
-    data: EntryData,
-}
-
-struct RGSSArchive {
-    magic: u32,
-    version: u8,
-    entry: Vec<Entry>,
-    stream: File,
-}
-
-impl RGSSArchive {
-    fn create(location: &str, version: u8) -> Result<Self, Error> {
-        let mut stream = File::create(location)?;
-        if version < 1 || version > 3 {
-            return Err(Error::new(ErrorKind::InvalidData, E_INVALIDVER));
-        }
-
-        stream.write_all(&[b'R', b'G', b'S', b'S', b'A', b'D', b'\0', version])?;
-
-        let magic = if version == 3 { 0u32 } else { 0xDEADCAFEu32 };
-        let entry = vec![];
-
-        Ok(RGSSArchive { magic, version, entry, stream })
-    }
-
-    fn open(location: &str) -> Result<Self, Error> {
-        let mut stream = File::open(location)?;
-
-        let mut header = [0u8; 8];
-        stream.read_exact(&mut header)?;
-
-        if &header[..6] != b"RGSSAD" {
-            return Err(Error::new(ErrorKind::InvalidData, E_INVALIDHDR));
-        }
-
-        // Check rgssad file version.
-        match header[7] {
-            1|2 => RGSSArchive::open_rgssad(stream, header[7]),
-              3 => RGSSArchive::open_rgss3a(stream, header[7]),
-              _ => Err(Error::new(ErrorKind::InvalidData, E_INVALIDVER)),
-        }
-    }
-
-    fn open_rgssad(mut stream: File, version: u8) -> Result<Self, Error> {
-        let mut magic = 0xDEADCAFEu32;
-        let mut entry = vec![];
-
-        loop {
-            let mut name_len: u32 = 0;
-            if ru32(&mut stream, &mut name_len).is_err() { break }
-            name_len ^= advance_magic(&mut magic);
-
-            let mut name = vec![0u8; name_len as usize];
-            stream.read_exact(&mut name)?;
-            for i in 0..(name_len as usize) {
-                name[i] ^= advance_magic(&mut magic) as u8;
-                if name[i] == b'\\' { name[i] = b'/' }
-            }
-            let name = String::from_utf8(name);
-            if name.is_err() { break }
-            let name = name.unwrap();
-
-            let mut data = EntryData { size: 0, offset: 0, magic: 0 };
-            if ru32(&mut stream, &mut data.size).is_err() { break }
-            data.size ^= advance_magic(&mut magic);
-            data.offset = stream.seek(SeekFrom::Current(0))? as u32;
-            data.magic = magic;
-
-            stream.seek(SeekFrom::Current(data.size as i64))?;
-            entry.push(Entry { name, data });
-        }
-
-        stream.seek(SeekFrom::Start(0))?;
-        Ok(RGSSArchive { magic, version, entry, stream })
-    }
-
-    fn open_rgss3a(mut stream: File, version: u8) -> Result<Self, Error> {
-        let mut magic = 0u32;
-        let mut entry = vec![];
-
-        if ru32(&mut stream, &mut magic).is_err() {
-            return Err(Error::new(ErrorKind::InvalidData, E_INVALIDMGC));
-        }
-        magic = magic.wrapping_mul(9).wrapping_add(3);
-
-        loop {
-            let mut offset: u32 = 0;
-            let mut size: u32 = 0;
-            let mut start_magic: u32 = 0;
-            let mut name_len: u32 = 0;
-
-            if ru32(&mut stream, &mut offset).is_err() { break };
-            offset ^= magic;
-
-            if offset == 0 { break }
-
-            if ru32(&mut stream, &mut size).is_err() { break }
-            size ^= magic;
-
-            if ru32(&mut stream, &mut start_magic).is_err() { break }
-            start_magic ^= magic;
-
-            if ru32(&mut stream, &mut name_len).is_err() { break }
-            name_len ^= magic;
-
-            let mut name = vec![0u8; name_len as usize];
-            stream.read_exact(&mut name)?;
-            for i in 0..(name_len as usize) {
-                name[i] ^= (magic >> 8*(i%4)) as u8;
-                if name[i] == b'\\' { name[i] = b'/' }
-            }
-            let name = String::from_utf8(name);
-            if name.is_err() { break }
-            let name = name.unwrap();
-
-            let data = EntryData { size, offset, magic: start_magic };
-
-            entry.push(Entry { name, data });
-        }
-
-        stream.seek(SeekFrom::Start(0))?;
-        Ok(RGSSArchive { magic, version, entry, stream })
-    }
-
-    fn write_entries(&mut self, root: &Path) -> Result<(), Error> {
-        match self.version {
-            1|2 => self.write_entries_rgssad(root),
-              3 => self.write_entries_rgss3a(root),
-              _ => Err(Error::new(ErrorKind::InvalidData, E_INVALIDVER)),
-        }
-    }
-
-    fn write_entries_rgssad(&mut self, root: &Path) -> Result<(), Error> {
-        let mut coder = Coder { buf: vec![0u8; 8192] };
-
-        for &Entry { ref name, ref data } in &self.entry {
-            println!("Packing: {}", name);
-
-            let mut name_len: u32 = name.len().try_into().unwrap();
-            name_len ^= advance_magic(&mut self.magic);
-            wu32(&mut self.stream, name_len)?;
-
-            let mut name_buf = name.as_bytes().to_vec();
-            for i in 0..name_buf.len() {
-                if name_buf[i] == b'/' { name_buf[i] = b'\\' }
-                name_buf[i] ^= advance_magic(&mut self.magic) as u8;
-            }
-            self.stream.write_all(&name_buf)?;
-
-            let mut size = data.size;
-            size ^= advance_magic(&mut self.magic);
-            wu32(&mut self.stream, size)?;
-
-            let mut file = File::open(root.join(name))?;
-            coder.copy(
-                &mut file,
-                &mut self.stream,
-                &EntryData {
-                    offset: 0,
-                    size: data.size,
-                    magic: self.magic,
-                }
-            )?;
-        }
-
-        Ok(())
-    }
-
-    fn write_entries_rgss3a(&mut self, root: &Path) -> Result<(), Error> {
-        // Layout is
-        //   +------+-----+-------+------+------+---+------+
-        //   |Header|Magic|Entries|File 1|File 2|...|File n|
-        //   +------+-----+-------+------+------+---+------+
-
-        // First calculate the offset to the end of Entries
-
-        let mut off: u32 = 8 + 4;  // Header + Magic
-        for &Entry { ref name, .. } in &self.entry {
-            // Each entry is 16 bytes + length of name
-            let name_len: u32 = name.len().try_into().unwrap();
-            off = off.checked_add(name_len).unwrap();
-            off = off.checked_add(16).unwrap();
-        }
-        off = off.checked_add(4).unwrap(); // terminates entry list
-
-        // Next calculate the offset for each entry.
-
-        for entry in &mut self.entry {
-            entry.data.offset = off;
-            off = off.checked_add(entry.data.size).unwrap();
-
-            // Also pick a magic for each entry. We can chose freely?
-            entry.data.magic = 0xDEADCAFEu32;
-        }
-
-        // Finally write it all out.
-
-        wu32(&mut self.stream, self.magic)?;
-        self.magic = self.magic.wrapping_mul(9).wrapping_add(3);
-
-        for &Entry { ref name, ref data } in &self.entry {
-            wu32(&mut self.stream, data.offset ^ self.magic)?;
-            wu32(&mut self.stream, data.size ^ self.magic)?;
-            wu32(&mut self.stream, data.magic ^ self.magic)?;
-            wu32(&mut self.stream, name.len() as u32 ^ self.magic)?;
-
-            let mut name_buf = name.as_bytes().to_vec();
-            for i in 0..name_buf.len() {
-                if name_buf[i] == b'/' { name_buf[i] = b'\\' }
-                name_buf[i] ^= (self.magic >> 8*(i%4)) as u8;
-            }
-            self.stream.write_all(&name_buf)?;
-        }
-        wu32(&mut self.stream, 0u32 ^ self.magic)?;
-
-        let mut coder = Coder { buf: vec![0u8; 8192] };
-
-        for &Entry { ref name, ref data } in &self.entry {
-            println!("Packing: {}", name);
-
-            let mut file = File::open(root.join(name))?;
-            coder.copy(
-                &mut file,
-                &mut self.stream,
-                &EntryData {
-                    offset: 0,
-                    size: data.size,
-                    magic: data.magic,
-                }
-            )?;
-        }
-
-        Ok(())
-    }
-}
+extern crate rgssad;
+use rgssad::{collect_entries, Entry, RGSSArchive};
+use rgssad::manifest::{self, ManifestEntry};
 
+static __VERSION__: &str = "0.1.4";
 
 fn usage() {
     println!("Extract rgssad/rgss2a/rgss3a files.
@@ -346,67 +20,100 @@ Commands:
     help
     version
     list        <archive>
-    unpack      <archive> <folder> [<filter>]
-    pack        <folder> <archive> [<version>]");
+    unpack      <archive> <dest> [<filter>] [--format dir|tar|zip] [--verify <manifest>] [--threads <n>]
+    pack        <folder> <archive> [<version>] [--manifest <manifest>] [--no-dedup]
+    verify      <archive> <manifest>
+    add         <archive> <name> <file>
+    update      <archive> <name> <file>
+    remove      <archive> <name>
+    mount       <archive> <mountpoint>");
 }
 
-fn list(archive: RGSSArchive) {
-    for Entry { name, data } in archive.entry {
+fn list(archive: RGSSArchive<fs::File>) {
+    for Entry { name, data } in archive.entries() {
         println!("{}: EntryData {{ size: {}, offset: {}, magic: {} }}", name, data.size, data.offset, data.magic);
     }
 }
 
-fn pack(src: &str, out: &str, version: u8) {
-    fn walkdir(archive: &mut RGSSArchive, d: &Path, r: &Path) {
-        for entry in fs::read_dir(&d).unwrap() {
-            let entry = entry.unwrap();
-            let path = entry.path();
-            if path.is_dir() {
-                walkdir(archive, &path, r);
-            } else {
-                let name = path.strip_prefix(r).unwrap().to_str().unwrap();
-                let size = fs::metadata(&path).unwrap().len();
-                let size: u32 = size.try_into().unwrap();
-
-                archive.entry.push(Entry {
-                    name: name.to_string(),
-                    data: EntryData {
-                        size,
-                        offset: 0, // calculated later
-                        magic: 0, // calculated later
-                    }
-                });
-            }
-        }
-    };
-
+fn pack(src: &str, out: &str, version: u8, manifest_path: Option<&str>, dedup: bool) {
     let root = Path::new(src);
     if !root.is_dir() {
         println!("FAILED: source is not a directory."); return
     }
 
-    let mut archive = match RGSSArchive::create(out, version) {
+    let mut archive = match RGSSArchive::create_path(out, version) {
         Ok(x) => x,
         Err(e) => {
             println!("FAILED: unable to create output file. {}", e); return
         }
     };
     // First pass: collect file names and sizes
-    walkdir(&mut archive, root, root);
+    if let Err(e) = collect_entries(&mut archive, root, root) {
+        println!("FAILED: unable to walk source directory. {}", e); return
+    }
     // Second pass: write file data.
-    if let Err(e) = archive.write_entries(root) {
+    if let Err(e) = archive.write_entries(root, dedup, Some(&mut |name: &str| println!("Packing: {}", name))) {
         println!("FAILED: unable to write archive. {}", e); return
     }
+
+    if let Some(manifest_path) = manifest_path {
+        let mut entries = vec![];
+        for Entry { name, data } in archive.entries() {
+            match manifest::crc32_file(&root.join(name)) {
+                Ok(crc32) => entries.push(ManifestEntry { name: name.clone(), size: data.size, crc32 }),
+                Err(e) => { println!("FAILED: unable to checksum {}. {}", name, e); return }
+            }
+        }
+
+        let mut file = match fs::File::create(manifest_path) {
+            Ok(f) => f,
+            Err(e) => { println!("FAILED: unable to create manifest. {}", e); return }
+        };
+        if let Err(e) = manifest::write_manifest(&mut file, &entries) {
+            println!("FAILED: unable to write manifest. {}", e);
+        }
+    }
 }
 
-fn unpack(mut archive: RGSSArchive, dir: &str, filter: &str) {
-    fn create(location: String) -> File {
+/// Extracts one entry, splitting the decryption across `threads` worker
+/// threads when asked for more than one. Parallel decryption needs
+/// positional reads, which are Unix-only, so non-Unix targets always take
+/// the serial path regardless of `threads`.
+#[cfg(unix)]
+fn extract_one<W: Write>(archive: &mut RGSSArchive<fs::File>, data: &rgssad::EntryData, threads: usize, writer: &mut W) -> io::Result<()> {
+    if threads > 1 {
+        writer.write_all(&archive.extract_entry_parallel(data, threads)?)
+    } else {
+        archive.extract_entry(data, writer)
+    }
+}
+
+#[cfg(not(unix))]
+fn extract_one<W: Write>(archive: &mut RGSSArchive<fs::File>, data: &rgssad::EntryData, _threads: usize, writer: &mut W) -> io::Result<()> {
+    archive.extract_entry(data, writer)
+}
+
+fn unpack(mut archive: RGSSArchive<fs::File>, dest: &str, filter: &str, format: &str, verify: Option<&str>, threads: usize) {
+    fn create(location: String) -> fs::File {
         let path = Path::new(location.as_str());
         fs::create_dir_all(path.parent().unwrap()).unwrap();
-        File::create(path.to_str().unwrap()).unwrap()
+        fs::File::create(path.to_str().unwrap()).unwrap()
     }
 
-    let entries = archive.entry.iter();
+    let manifest: Option<HashMap<String, ManifestEntry>> = match verify {
+        Some(path) => {
+            let file = match fs::File::open(path) {
+                Ok(f) => f,
+                Err(e) => { println!("FAILED: unable to open manifest {}. {}", path, e); return }
+            };
+            match manifest::read_manifest(file) {
+                Ok(entries) => Some(entries.into_iter().map(|e| (e.name.clone(), e)).collect()),
+                Err(e) => { println!("FAILED: unable to read manifest {}. {}", path, e); return }
+            }
+        },
+        None => None,
+    };
+
     let filter = match Regex::new(filter) {
         Ok(re) => re,
         Err(_) => {
@@ -414,17 +121,73 @@ fn unpack(mut archive: RGSSArchive, dir: &str, filter: &str) {
         }
     };
 
-    let mut coder = Coder { buf: vec![0u8; 8192] };
-
-    for Entry { name, data } in entries {
-        if !filter.is_match(name) { continue }
-
-        println!("Extracting: {}", name);
-
-        let mut file = create(format!("{}/{}", dir, name));
-        if let Err(err) = coder.copy(&mut archive.stream, &mut file, data) {
-            println!("FAILED: key save failed, {}", err.to_string()); return
-        }
+    let indices: Vec<usize> = archive.entries().iter().enumerate()
+        .filter(|(_, Entry { name, .. })| filter.is_match(name))
+        .map(|(i, _)| i)
+        .collect();
+
+    match format {
+        #[cfg(feature = "tar")]
+        "tar" => {
+            let mut file = match fs::File::create(dest) {
+                Ok(f) => f,
+                Err(e) => { println!("FAILED: unable to create {}. {}", dest, e); return }
+            };
+            if let Err(e) = rgssad::container::write_tar(&mut archive, &indices, &mut file, manifest.as_ref()) {
+                println!("FAILED: unable to write tar archive. {}", e);
+            }
+        },
+        #[cfg(feature = "zip")]
+        "zip" => {
+            let file = match fs::File::create(dest) {
+                Ok(f) => f,
+                Err(e) => { println!("FAILED: unable to create {}. {}", dest, e); return }
+            };
+            if let Err(e) = rgssad::container::write_zip(&mut archive, &indices, file, manifest.as_ref()) {
+                println!("FAILED: unable to write zip archive. {}", e);
+            }
+        },
+        #[cfg(not(feature = "tar"))]
+        "tar" => {
+            println!("FAILED: built without the `tar` feature; cannot write tar output");
+        },
+        #[cfg(not(feature = "zip"))]
+        "zip" => {
+            println!("FAILED: built without the `zip` feature; cannot write zip output");
+        },
+        "dir" => {
+            let entries: Vec<(String, rgssad::EntryData)> = indices.iter()
+                .map(|&i| { let Entry { name, data } = &archive.entries()[i]; (name.clone(), *data) })
+                .collect();
+
+            for (name, data) in entries {
+                println!("Extracting: {}", name);
+
+                let file = create(format!("{}/{}", dest, name));
+                let mut writer = manifest::ChecksumWriter::new(file);
+                if let Err(err) = extract_one(&mut archive, &data, threads, &mut writer) {
+                    println!("FAILED: key save failed, {}", err); return
+                }
+                let (_, crc32) = writer.finish();
+
+                if let Some(manifest) = &manifest {
+                    match manifest.get(&name) {
+                        Some(expected) if expected.crc32 == crc32 && expected.size == data.size => {},
+                        Some(expected) => {
+                            println!("FAILED: checksum mismatch for {}: expected {:08x}, got {:08x}", name, expected.crc32, crc32);
+                            return
+                        },
+                        None => {
+                            println!("FAILED: {} not present in manifest", name);
+                            return
+                        },
+                    }
+                }
+            }
+        },
+        other => {
+            println!("FAILED: unknown --format {}, expected dir, tar, or zip", other);
+        },
     }
 }
 
@@ -439,41 +202,167 @@ fn main() {
         },
         "list" => {
             assert!(args.len() == 3);
-            let archive = RGSSArchive::open(args[2].as_str());
+            let archive = RGSSArchive::open_path(args[2].as_str());
             if let Err(err) = archive {
-                println!("FAILED: file parse failed, {}", err.to_string()); return;
+                println!("FAILED: file parse failed, {}", err); return;
             }
             let archive = archive.unwrap();
 
             list(archive);
         },
         "unpack" => {
-            assert!(args.len() > 3 && args.len() < 6);
-            let archive = RGSSArchive::open(args[2].as_str());
+            let mut positional = vec![];
+            let mut format = "dir";
+            let mut verify = None;
+            let mut threads = 1;
+            let mut i = 2;
+            while i < args.len() {
+                if args[i] == "--format" {
+                    if i + 1 >= args.len() { println!("FAILED: --format requires a value"); return }
+                    i += 1;
+                    format = args[i].as_str();
+                } else if args[i] == "--verify" {
+                    if i + 1 >= args.len() { println!("FAILED: --verify requires a value"); return }
+                    i += 1;
+                    verify = Some(args[i].as_str());
+                } else if args[i] == "--threads" {
+                    if i + 1 >= args.len() { println!("FAILED: --threads requires a value"); return }
+                    i += 1;
+                    threads = args[i].parse().unwrap_or(1);
+                } else {
+                    positional.push(args[i].as_str());
+                }
+                i += 1;
+            }
+            assert!(positional.len() > 1 && positional.len() < 4);
+
+            let archive = RGSSArchive::open_path(positional[0]);
             if let Err(err) = archive {
-                println!("FAILED: file parse failed, {}", err.to_string()); return;
+                println!("FAILED: file parse failed, {}", err); return;
             }
             let archive = archive.unwrap();
-            unpack(archive, args[3].as_str(), if args.len() == 5 { args[4].as_str() } else { ".*" });
+            unpack(archive, positional[1], if positional.len() == 3 { positional[2] } else { ".*" }, format, verify, threads);
         },
         "pack" => {
-            assert!(args.len() > 3 && args.len() < 6);
-            let mut version = if args[3].ends_with(".rgss3a") {
+            let mut positional = vec![];
+            let mut manifest_path = None;
+            let mut dedup = true;
+            let mut i = 2;
+            while i < args.len() {
+                if args[i] == "--manifest" {
+                    if i + 1 >= args.len() { println!("FAILED: --manifest requires a value"); return }
+                    i += 1;
+                    manifest_path = Some(args[i].as_str());
+                } else if args[i] == "--no-dedup" {
+                    dedup = false;
+                } else {
+                    positional.push(args[i].as_str());
+                }
+                i += 1;
+            }
+            assert!(positional.len() > 1 && positional.len() < 4);
+
+            let mut version = if positional[1].ends_with(".rgss3a") {
                 3
-            } else if args[3].ends_with(".rgss2a") {
+            } else if positional[1].ends_with(".rgss2a") {
                 2
             } else {
                 1
             };
-            if args.len() == 5 {
-                version = match args[4].parse() {
+            if positional.len() == 3 {
+                version = match positional[2].parse() {
                     Ok(v) => v,
                     Err(_) => {
-                        println!("FAILED: {}", E_INVALIDVER); return
+                        println!("FAILED: {}", rgssad::E_INVALIDVER); return
                     }
                 }
             };
-            pack(args[2].as_str(), args[3].as_str(), version);
+            pack(positional[0], positional[1], version, manifest_path, dedup);
+        },
+        "verify" => {
+            assert!(args.len() == 4);
+            let archive = RGSSArchive::open_path(args[2].as_str());
+            if let Err(err) = archive {
+                println!("FAILED: file parse failed, {}", err); return;
+            }
+            let mut archive = archive.unwrap();
+
+            let file = match fs::File::open(args[3].as_str()) {
+                Ok(f) => f,
+                Err(e) => { println!("FAILED: unable to open manifest {}. {}", args[3], e); return }
+            };
+            let expected: HashMap<String, ManifestEntry> = match manifest::read_manifest(file) {
+                Ok(entries) => entries.into_iter().map(|e| (e.name.clone(), e)).collect(),
+                Err(e) => { println!("FAILED: unable to read manifest {}. {}", args[3], e); return }
+            };
+
+            let entries: Vec<(String, rgssad::EntryData)> = archive.entries().iter()
+                .map(|Entry { name, data }| (name.clone(), *data))
+                .collect();
+
+            for (name, data) in entries {
+                let expected = match expected.get(&name) {
+                    Some(e) => e,
+                    None => { println!("FAILED: {} not present in manifest", name); return }
+                };
+
+                let mut writer = manifest::ChecksumWriter::new(io::sink());
+                if let Err(err) = archive.extract_entry(&data, &mut writer) {
+                    println!("FAILED: unable to read {}. {}", name, err); return
+                }
+                let (_, crc32) = writer.finish();
+
+                if crc32 != expected.crc32 || data.size != expected.size {
+                    println!("FAILED: checksum mismatch for {}: expected {:08x}, got {:08x}", name, expected.crc32, crc32);
+                    return
+                }
+            }
+
+            println!("OK: {} entries verified", archive.entries().len());
+        },
+        "add" | "update" => {
+            assert!(args.len() == 5);
+            let archive = RGSSArchive::open_path_rw(args[2].as_str());
+            if let Err(err) = archive {
+                println!("FAILED: file parse failed, {}", err); return;
+            }
+            let mut archive = archive.unwrap();
+
+            let mut print_progress = |name: &str| println!("Packing: {}", name);
+            let progress: rgssad::Progress = Some(&mut print_progress);
+            let result = if args[1] == "add" {
+                archive.add_entry(args[3].as_str(), Path::new(args[4].as_str()), progress)
+            } else {
+                archive.update_entry(args[3].as_str(), Path::new(args[4].as_str()), progress)
+            };
+            if let Err(err) = result {
+                println!("FAILED: {}, {}", args[1], err);
+            }
+        },
+        "remove" => {
+            assert!(args.len() == 4);
+            let archive = RGSSArchive::open_path_rw(args[2].as_str());
+            if let Err(err) = archive {
+                println!("FAILED: file parse failed, {}", err); return;
+            }
+            let mut archive = archive.unwrap();
+
+            let mut print_progress = |name: &str| println!("Packing: {}", name);
+            let progress: rgssad::Progress = Some(&mut print_progress);
+            if let Err(err) = archive.remove_entry(args[3].as_str(), progress) {
+                println!("FAILED: remove, {}", err);
+            }
+        },
+        #[cfg(feature = "fuse")]
+        "mount" => {
+            assert!(args.len() == 4);
+            let archive = RGSSArchive::open_path(args[2].as_str());
+            if let Err(err) = archive {
+                println!("FAILED: file parse failed, {}", err); return;
+            }
+            if let Err(err) = rgssad::mount::mount(archive.unwrap(), args[3].as_str()) {
+                println!("FAILED: mount failed, {}", err);
+            }
         },
         _ => usage(),
     }
@@ -0,0 +1,227 @@
+//! Read-only FUSE view onto an opened archive.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{Error, ErrorKind, Read};
+use std::time::{Duration, UNIX_EPOCH};
+
+use fuser::{FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request};
+
+use crate::{Entry, RGSSArchive};
+
+const TTL: Duration = Duration::from_secs(1);
+const ROOT_INO: u64 = 1;
+
+enum Node {
+    Dir { children: HashMap<String, u64> },
+    File { entry_index: usize },
+}
+
+/// Builds the inode tree from entry names, descending into `Dir` nodes as
+/// `/`-separated path components are consumed. Errors out instead of
+/// silently dropping an entry when one entry's name is a path-prefix of
+/// another's (e.g. both `a/b` and `a/b/c` present), since that would require
+/// turning an already-allocated `File` node into a `Dir` partway through.
+fn build_tree(entries: &[Entry]) -> Result<Vec<Node>, Error> {
+    let mut nodes = vec![Node::Dir { children: HashMap::new() }]; // ino 1 = root
+
+    for (index, entry) in entries.iter().enumerate() {
+        let mut parent = ROOT_INO;
+        let mut parts = entry.name.split('/').peekable();
+        while let Some(part) = parts.next() {
+            let is_last = parts.peek().is_none();
+            let existing = match &nodes[(parent - 1) as usize] {
+                Node::Dir { children } => children.get(part).copied(),
+                Node::File { .. } => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("{} is both a file and a directory prefix", entry.name),
+                    ));
+                },
+            };
+            if let (true, Some(ino)) = (is_last, existing) {
+                if let Node::Dir { .. } = &nodes[(ino - 1) as usize] {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("{} is both a file and a directory prefix", entry.name),
+                    ));
+                }
+            }
+            let child_ino = match existing {
+                Some(ino) => ino,
+                None => {
+                    let ino = nodes.len() as u64 + 1;
+                    nodes.push(if is_last {
+                        Node::File { entry_index: index }
+                    } else {
+                        Node::Dir { children: HashMap::new() }
+                    });
+                    if let Node::Dir { children } = &mut nodes[(parent - 1) as usize] {
+                        children.insert(part.to_string(), ino);
+                    }
+                    ino
+                }
+            };
+            parent = child_ino;
+        }
+    }
+
+    Ok(nodes)
+}
+
+/// Read-only FUSE filesystem backed by an already-opened `RGSSArchive`.
+pub struct ArchiveFs {
+    archive: RGSSArchive<fs::File>,
+    nodes: Vec<Node>, // indexed by ino - 1
+}
+
+impl ArchiveFs {
+    pub fn new(archive: RGSSArchive<fs::File>) -> Result<Self, Error> {
+        let nodes = build_tree(archive.entries())?;
+        Ok(ArchiveFs { archive, nodes })
+    }
+
+    fn attr(&self, ino: u64) -> FileAttr {
+        let (kind, size) = match &self.nodes[(ino - 1) as usize] {
+            Node::Dir { .. } => (FileType::Directory, 0u64),
+            Node::File { entry_index } => (FileType::RegularFile, self.archive.entries()[*entry_index].data.size as u64),
+        };
+        FileAttr {
+            ino,
+            size,
+            blocks: (size + 511) / 512,
+            atime: UNIX_EPOCH,
+            mtime: UNIX_EPOCH,
+            ctime: UNIX_EPOCH,
+            crtime: UNIX_EPOCH,
+            kind,
+            perm: if kind == FileType::Directory { 0o755 } else { 0o444 },
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            blksize: 512,
+            flags: 0,
+        }
+    }
+}
+
+impl Filesystem for ArchiveFs {
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let name = match name.to_str() {
+            Some(n) => n,
+            None => { reply.error(libc::ENOENT); return }
+        };
+        let ino = match &self.nodes[(parent - 1) as usize] {
+            Node::Dir { children } => children.get(name).copied(),
+            Node::File { .. } => None,
+        };
+        match ino {
+            Some(ino) => reply.entry(&TTL, &self.attr(ino), 0),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+        if ino == 0 || ino as usize > self.nodes.len() { reply.error(libc::ENOENT); return }
+        reply.attr(&TTL, &self.attr(ino));
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let entry_index = match &self.nodes[(ino - 1) as usize] {
+            Node::File { entry_index } => *entry_index,
+            Node::Dir { .. } => { reply.error(libc::EISDIR); return }
+        };
+        let data = self.archive.entries()[entry_index].data;
+        let offset = offset.max(0) as u32;
+        if offset >= data.size { reply.data(&[]); return }
+
+        let len = size.min(data.size - offset);
+        let mut reader = match self.archive.entry_reader_at(&data, offset) {
+            Ok(r) => r,
+            Err(_) => { reply.error(libc::EIO); return }
+        };
+        let mut buf = vec![0u8; len as usize];
+        match reader.read_exact(&mut buf) {
+            Ok(()) => reply.data(&buf),
+            Err(_) => reply.error(libc::EIO),
+        }
+    }
+
+    fn readdir(&mut self, _req: &Request, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+        let children: Vec<(u64, FileType, String)> = match &self.nodes[(ino - 1) as usize] {
+            Node::Dir { children } => children.iter().map(|(name, &child_ino)| {
+                let kind = match &self.nodes[(child_ino - 1) as usize] {
+                    Node::Dir { .. } => FileType::Directory,
+                    Node::File { .. } => FileType::RegularFile,
+                };
+                (child_ino, kind, name.clone())
+            }).collect(),
+            Node::File { .. } => { reply.error(libc::ENOTDIR); return }
+        };
+
+        let mut entries = vec![(ino, FileType::Directory, ".".to_string()), (ino, FileType::Directory, "..".to_string())];
+        entries.extend(children);
+
+        for (i, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+            if reply.add(ino, (i + 1) as i64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+/// Mounts `archive` read-only at `mountpoint` until interrupted.
+pub fn mount(archive: RGSSArchive<fs::File>, mountpoint: &str) -> std::io::Result<()> {
+    let options = [fuser::MountOption::RO, fuser::MountOption::FSName("rgssad".to_string())];
+    fuser::mount2(ArchiveFs::new(archive)?, mountpoint, &options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EntryData;
+
+    fn entry(name: &str) -> Entry {
+        Entry { name: name.to_string(), data: EntryData { offset: 0, size: 0, magic: 0 } }
+    }
+
+    #[test]
+    fn build_tree_links_nested_directories_to_root() {
+        let entries = vec![entry("a/b.txt"), entry("a/c/d.txt")];
+        let nodes = build_tree(&entries).unwrap();
+
+        let root = match &nodes[0] {
+            Node::Dir { children } => children,
+            Node::File { .. } => panic!("root must be a dir"),
+        };
+        let a = match &nodes[(root["a"] - 1) as usize] {
+            Node::Dir { children } => children,
+            Node::File { .. } => panic!("a must be a dir"),
+        };
+        assert!(matches!(&nodes[(a["b.txt"] - 1) as usize], Node::File { .. }));
+        let c = match &nodes[(a["c"] - 1) as usize] {
+            Node::Dir { children } => children,
+            Node::File { .. } => panic!("a/c must be a dir"),
+        };
+        assert!(matches!(&nodes[(c["d.txt"] - 1) as usize], Node::File { .. }));
+    }
+
+    #[test]
+    fn build_tree_rejects_a_file_used_as_a_directory_prefix() {
+        let entries = vec![entry("a/b"), entry("a/b/c")];
+        assert!(build_tree(&entries).is_err());
+    }
+}